@@ -0,0 +1,29 @@
+use serde::Serialize;
+
+/// Identifies the service (and optional version) that produced a log entry, used to group
+/// entries reported to [Cloud Error Reporting](https://cloud.google.com/error-reporting/docs/formatting-error-messages)
+/// (see [`Stackdriver::with_error_reporting`](crate::Stackdriver::with_error_reporting)).
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceContext {
+    /// name of the service
+    pub service: String,
+    /// version of the service, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl ServiceContext {
+    /// Create a new `ServiceContext` for the given service name, with no version set.
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            version: None,
+        }
+    }
+
+    /// Set the service version.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+}
@@ -1,28 +1,110 @@
-use crate::visitor::{StackdriverEventVisitor, StackdriverVisitor};
+use crate::{
+    error_reporting::ServiceContext,
+    resource::MonitoredResource,
+    trace::TraceContext,
+    visitor::{StackdriverEventVisitor, StackdriverVisitor},
+};
 use serde::ser::{SerializeMap, Serializer as _};
 use serde_json::Value;
 use std::{
+    collections::HashMap,
     fmt::{self, Write},
     io,
+    time::Instant,
+};
+use tracing_core::{
+    field::Visit,
+    span::{Attributes, Id},
+    Event, Field, Subscriber,
 };
-use tracing_core::{span::{Attributes, Id}, Event, Subscriber};
 use tracing_serde::AsSerde;
 use tracing_subscriber::{
-    field::{MakeVisitor, VisitOutput},
-    fmt::{time::UtcTime, FormatFields, FormattedFields, MakeWriter},
+    field::{MakeVisitor, RecordFields, VisitOutput},
+    fmt::{
+        format::{FmtSpan, JsonFields, Writer},
+        time::{FormatTime, UtcTime},
+        FormatFields, FormattedFields, MakeWriter,
+    },
     layer::Context,
     registry::LookupSpan,
     Layer,
 };
-use time::format_description::well_known;
+use time::{format_description::well_known, OffsetDateTime};
+
+/// Controls how fields from the active span stack are attached to a log entry when
+/// span logging is enabled (see [`Stackdriver::with_span_layout`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanLayout {
+    /// Merge the fields of every span from root to leaf into a single `span` object,
+    /// with fields on more deeply nested spans taking precedence on conflicts.
+    Flatten,
+    /// Emit a `spans` array of `{ span_name, ...fields }` objects, ordered from root to leaf,
+    /// preserving each span's own name and fields.
+    Nested,
+}
+
+/// Names of the event fields collected into a structured `httpRequest` LogEntry key instead of
+/// flowing into the payload (see [`Stackdriver::with_http_request_fields`]). Defaults to the
+/// `http.*` field names below.
+#[derive(Debug, Clone)]
+pub struct HttpRequestFields {
+    /// field mapped to `requestMethod`, defaults to `"http.method"`
+    pub method: String,
+    /// field mapped to `requestUrl`, defaults to `"http.url"`
+    pub url: String,
+    /// field mapped to `status`, defaults to `"http.status_code"`
+    pub status: String,
+    /// field mapped to `latency` (serialized as `"<seconds>s"`), defaults to
+    /// `"http.latency_seconds"`
+    pub latency_seconds: String,
+    /// field mapped to `remoteIp`, defaults to `"http.remote_ip"`
+    pub remote_ip: String,
+    /// field mapped to `userAgent`, defaults to `"http.user_agent"`
+    pub user_agent: String,
+}
+
+impl Default for HttpRequestFields {
+    fn default() -> Self {
+        Self {
+            method: "http.method".to_owned(),
+            url: "http.url".to_owned(),
+            status: "http.status_code".to_owned(),
+            latency_seconds: "http.latency_seconds".to_owned(),
+            remote_ip: "http.remote_ip".to_owned(),
+            user_agent: "http.user_agent".to_owned(),
+        }
+    }
+}
+
+/// Controls how the `time` LogEntry key is serialized (see
+/// [`Stackdriver::with_timestamp_format`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    /// An RFC3339-formatted string, e.g. `"2023-01-01T00:00:00.123456789Z"` (default).
+    #[default]
+    Rfc3339,
+    /// The Google [`Timestamp`](https://protobuf.dev/reference/protobuf/google.protobuf/#timestamp)
+    /// structured form Cloud Logging accepts directly: `{ "seconds": i64, "nanos": i32 }`.
+    UnixTimestamp,
+    /// Milliseconds since the Unix epoch, as a raw number.
+    EpochMillis,
+}
 
 /// A tracing adapter for stackdriver
 pub struct Stackdriver<W = fn() -> io::Stdout>
 {
     time: UtcTime<well_known::Rfc3339>,
+    timestamp_format: TimestampFormat,
     make_writer: W,
     fields: StackdriverFields,
     log_span: bool,
+    span_layout: SpanLayout,
+    span_events: FmtSpan,
+    project_id: Option<String>,
+    error_reporting: Option<ServiceContext>,
+    resource: Option<MonitoredResource>,
+    labels: HashMap<String, String>,
+    http_request_fields: Option<HttpRequestFields>,
 }
 
 impl Stackdriver {
@@ -40,11 +122,97 @@ impl<W> Stackdriver<W> {
     {
         Stackdriver {
             time: UtcTime::rfc_3339(),
+            timestamp_format: self.timestamp_format,
             make_writer,
-            fields: StackdriverFields,
-            log_span: false,
+            fields: StackdriverFields::default(),
+            log_span: self.log_span,
+            span_layout: self.span_layout,
+            span_events: self.span_events,
+            project_id: self.project_id,
+            error_reporting: self.error_reporting,
+            resource: self.resource,
+            labels: self.labels,
+            http_request_fields: self.http_request_fields,
         }
     }
+
+    /// Set the GCP project id used to build the `logging.googleapis.com/trace` resource name
+    /// (`projects/<project_id>/traces/<trace_id>`) for log entries with trace context.
+    ///
+    /// Without a `project_id`, trace/span correlation fields are omitted even when trace
+    /// context is present on the current span.
+    pub fn with_project_id(mut self, project_id: impl Into<String>) -> Self {
+        self.project_id = Some(project_id.into());
+        self
+    }
+
+    /// Choose how fields from the active span stack are attached to a log entry. Defaults
+    /// to [`SpanLayout::Flatten`]. Has no effect unless span logging is enabled.
+    pub fn with_span_layout(mut self, span_layout: SpanLayout) -> Self {
+        self.span_layout = span_layout;
+        self
+    }
+
+    /// Toggle whether fields from the active span stack are attached to each log entry
+    /// under a `span` (or `spans`, see [`SpanLayout`]) key. Disabled by default.
+    pub fn with_span_logging(mut self, log_span: bool) -> Self {
+        self.log_span = log_span;
+        self
+    }
+
+    /// Opt in to Bunyan-style span lifecycle log entries. Accepts any combination of
+    /// [`FmtSpan::NEW`] and [`FmtSpan::CLOSE`] (other `FmtSpan` flags have no effect):
+    /// `NEW` emits an entry when a span is created, `CLOSE` emits one when it closes,
+    /// including an `elapsed_milliseconds` measurement of the span's lifetime. Disabled
+    /// (`FmtSpan::NONE`) by default.
+    pub fn with_span_events(mut self, span_events: FmtSpan) -> Self {
+        self.span_events = span_events;
+        self
+    }
+
+    /// Opt in to [Cloud Error Reporting](https://cloud.google.com/error-reporting/docs/formatting-error-messages)
+    /// integration: `ERROR`-level events are stamped with `@type`, `serviceContext`, and a
+    /// `stack_trace` built from the event's message and the `source()` chain of any `error`
+    /// field recorded on it. Disabled by default.
+    pub fn with_error_reporting(mut self, service_context: ServiceContext) -> Self {
+        self.error_reporting = Some(service_context);
+        self
+    }
+
+    /// Attach a [`MonitoredResource`] describing the environment log entries are produced in,
+    /// serialized under the `resource` key. See [`detect_resource`](crate::detect_resource) for
+    /// a convenience that detects this automatically on GCE, GKE, and Cloud Run.
+    pub fn with_resource(mut self, resource: MonitoredResource) -> Self {
+        self.resource = Some(resource);
+        self
+    }
+
+    /// Attach static labels to every log entry's `logging.googleapis.com/labels`. Can be called
+    /// multiple times; labels accumulate, with later calls overwriting keys set by earlier ones.
+    pub fn with_labels<K, V>(mut self, labels: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.labels
+            .extend(labels.into_iter().map(|(key, value)| (key.into(), value.into())));
+        self
+    }
+
+    /// Collect the configured event field names into a structured `httpRequest` LogEntry key
+    /// (method, URL, status, latency, remote IP, user agent) instead of letting them flow into
+    /// the payload like any other field. Disabled by default; see [`HttpRequestFields`] for the
+    /// field names matched once enabled.
+    pub fn with_http_request_fields(mut self, http_request_fields: HttpRequestFields) -> Self {
+        self.http_request_fields = Some(http_request_fields);
+        self
+    }
+
+    /// Choose how the `time` LogEntry key is serialized. Defaults to [`TimestampFormat::Rfc3339`].
+    pub fn with_timestamp_format(mut self, timestamp_format: TimestampFormat) -> Self {
+        self.timestamp_format = timestamp_format;
+        self
+    }
 }
 
 impl<W> Stackdriver<W>
@@ -58,15 +226,12 @@ where
     {
         let mut buffer: Vec<u8> = Default::default();
         let meta = event.metadata();
-        let mut time = String::new();
-
-        // self.time.format_time(&mut time).map_err(|_| Error::Time)?;
 
         let mut serializer = serde_json::Serializer::new(&mut buffer);
 
         let mut map = serializer.serialize_map(None)?;
 
-        map.serialize_entry("time", &time)?;
+        self.write_time(&mut map)?;
         map.serialize_entry("severity", &meta.level().as_serde())?;
         map.serialize_entry("logger", &meta.target())?;
         map.serialize_entry(
@@ -78,34 +243,199 @@ where
         )?;
 
         if self.log_span {
+            if let Some(scope) = context.event_scope(event) {
+                match self.span_layout {
+                    SpanLayout::Flatten => {
+                        let mut fields = serde_json::Map::new();
+
+                        for span in scope.from_root() {
+                            let extensions = span.extensions();
+
+                            if let Some(formatted_fields) =
+                                extensions.get::<FormattedFields<StackdriverFields>>()
+                            {
+                                // TODO: include serializable data type in extensions instead of str
+                                if let Value::Object(span_fields) =
+                                    serde_json::from_str(formatted_fields)?
+                                {
+                                    fields.extend(span_fields);
+                                }
+                            }
+
+                            fields.insert(
+                                "span_name".to_string(),
+                                serde_json::json!(span.name()),
+                            );
+                        }
+
+                        map.serialize_entry("span", &fields)?;
+                    }
+                    SpanLayout::Nested => {
+                        let mut spans = Vec::new();
+
+                        for span in scope.from_root() {
+                            let extensions = span.extensions();
+
+                            let mut fields = match extensions
+                                .get::<FormattedFields<StackdriverFields>>()
+                            {
+                                // TODO: include serializable data type in extensions instead of str
+                                Some(formatted_fields) => serde_json::from_str(formatted_fields)?,
+                                None => Value::Object(serde_json::Map::new()),
+                            };
+
+                            fields["span_name"] = serde_json::json!(span.name());
+
+                            spans.push(fields);
+                        }
+
+                        map.serialize_entry("spans", &spans)?;
+                    }
+                }
+            }
+        }
+
+        if let Some(resource) = &self.resource {
+            map.serialize_entry("resource", resource)?;
+        }
+
+        if !self.labels.is_empty() {
+            map.serialize_entry("logging.googleapis.com/labels", &self.labels)?;
+        }
+
+        if let Some(project_id) = &self.project_id {
             if let Some(span) = context.lookup_current() {
-                let name = &span.name();
-                let extensions = span.extensions();
-                let formatted_fields = extensions
-                    .get::<FormattedFields<StackdriverFields>>()
-                    .expect("No fields!");
+                let trace = span
+                    .scope()
+                    .find_map(|span| span.extensions().get::<TraceContext>().cloned());
 
-                // TODO: include serializable data type in extensions instead of str
-                let mut fields: Value = serde_json::from_str(&formatted_fields)?;
+                if let Some(trace) = trace {
+                    map.serialize_entry(
+                        "logging.googleapis.com/trace",
+                        &format!("projects/{}/traces/{}", project_id, trace.trace_id),
+                    )?;
+                    map.serialize_entry("logging.googleapis.com/spanId", &trace.span_id)?;
+                    map.serialize_entry("logging.googleapis.com/trace_sampled", &trace.sampled)?;
+                }
+            }
+        }
 
-                fields["name"] = serde_json::json!(name);
+        if let Some(service_context) = &self.error_reporting {
+            if *meta.level() == tracing_core::Level::ERROR {
+                let mut error_chain = ErrorChainVisitor::default();
+                event.record(&mut error_chain);
 
-                map.serialize_entry("span", &fields)?;
+                map.serialize_entry(
+                    "@type",
+                    "type.googleapis.com/google.devtools.clouderrorreporting.v1beta1.ReportedErrorEvent",
+                )?;
+                map.serialize_entry("serviceContext", service_context)?;
+                map.serialize_entry(
+                    "stack_trace",
+                    &error_chain.into_stack_trace(meta.file(), meta.line()),
+                )?;
             }
         }
 
         // TODO: enable deeper structuring of keys and values across tracing
         // https://github.com/tokio-rs/tracing/issues/663
-        let mut visitor = StackdriverEventVisitor::new(map);
+        let mut visitor = StackdriverEventVisitor::new(map, self.http_request_fields.as_ref());
 
         event.record(&mut visitor);
 
         visitor.finish().map_err(Error::from)?;
 
+        self.emit(buffer)
+    }
+
+    /// Write a Bunyan-style span lifecycle entry (see [`Stackdriver::with_span_events`]).
+    /// `elapsed_milliseconds` is `Some` for a `CLOSE` entry and `None` for a `NEW` entry.
+    fn write_span_event<S>(
+        &self,
+        span: &tracing_subscriber::registry::SpanRef<S>,
+        elapsed_milliseconds: Option<u128>,
+    ) -> Result<(), Error>
+    where
+        S: for<'span> LookupSpan<'span>,
+    {
+        let mut buffer: Vec<u8> = Default::default();
+        let meta = span.metadata();
+
+        let mut serializer = serde_json::Serializer::new(&mut buffer);
+        let mut map = serializer.serialize_map(None)?;
+
+        self.write_time(&mut map)?;
+        map.serialize_entry("severity", &meta.level().as_serde())?;
+        map.serialize_entry("logger", &meta.target())?;
+        map.serialize_entry(
+            "logging.googleapis.com/sourceLocation",
+            &SourceLocation {
+                line: meta.line(),
+                file: meta.file(),
+            },
+        )?;
+
+        let extensions = span.extensions();
+        let mut fields: Value = match extensions.get::<FormattedFields<StackdriverFields>>() {
+            // TODO: include serializable data type in extensions instead of str
+            Some(formatted_fields) => serde_json::from_str(formatted_fields)?,
+            None => Value::Object(serde_json::Map::new()),
+        };
+        fields["span_name"] = serde_json::json!(span.name());
+        map.serialize_entry("span", &fields)?;
+
+        let message = match elapsed_milliseconds {
+            Some(elapsed_milliseconds) => {
+                map.serialize_entry("elapsed_milliseconds", &elapsed_milliseconds)?;
+                format!("[{} - END]", span.name())
+            }
+            None => format!("[{} - START]", span.name()),
+        };
+        map.serialize_entry("message", &message)?;
+
+        map.end()?;
+
+        self.emit(buffer)
+    }
+
+    /// Serialize the `time` LogEntry key in the configured [`TimestampFormat`].
+    fn write_time<M>(&self, map: &mut M) -> Result<(), Error>
+    where
+        M: SerializeMap<Error = serde_json::Error>,
+    {
+        match self.timestamp_format {
+            TimestampFormat::Rfc3339 => {
+                let mut time = String::new();
+                self.time
+                    .format_time(&mut Writer::new(&mut time))
+                    .map_err(|_| Error::Time)?;
+                map.serialize_entry("time", &time)?;
+            }
+            TimestampFormat::UnixTimestamp => {
+                let now = OffsetDateTime::now_utc();
+                map.serialize_entry(
+                    "time",
+                    &UnixTimestamp {
+                        seconds: now.unix_timestamp(),
+                        nanos: now.nanosecond() as i32,
+                    },
+                )?;
+            }
+            TimestampFormat::EpochMillis => {
+                let now = OffsetDateTime::now_utc();
+                let millis = now.unix_timestamp() * 1000 + i64::from(now.millisecond());
+                map.serialize_entry("time", &millis)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit(&self, mut buffer: Vec<u8>) -> Result<(), Error> {
         use std::io::Write;
         let mut writer = self.make_writer.make_writer();
         buffer.write_all(b"\n")?;
-        writer.write_all(&mut buffer)?;
+        writer.write_all(&buffer)?;
         Ok(())
     }
 }
@@ -114,9 +444,17 @@ impl Default for Stackdriver {
     fn default() -> Self {
         Self {
             time: UtcTime::rfc_3339(),
+            timestamp_format: TimestampFormat::default(),
             make_writer: std::io::stdout,
-            fields: StackdriverFields,
+            fields: StackdriverFields::default(),
             log_span: false,
+            span_layout: SpanLayout::Flatten,
+            span_events: FmtSpan::NONE,
+            project_id: None,
+            error_reporting: None,
+            resource: None,
+            labels: HashMap::new(),
+            http_request_fields: None,
         }
     }
 }
@@ -126,6 +464,59 @@ where
     S: Subscriber + for<'span> LookupSpan<'span>,
     W: for<'writer> MakeWriter<'writer> + 'static,
 {
+    #[allow(unused_variables)]
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+        let span = context.span(id).expect("Span not found, this is a bug");
+        let mut extensions = span.extensions_mut();
+
+        if extensions
+            .get_mut::<FormattedFields<StackdriverFields>>()
+            .is_none()
+        {
+            let mut fields = FormattedFields::<StackdriverFields>::new(String::new());
+
+            if self.fields.format_fields(fields.as_writer(), attributes).is_ok() {
+                extensions.insert(fields);
+            }
+        }
+
+        // pick up trace context from an incoming W3C `traceparent` field recorded directly on
+        // the span.
+        let mut visitor = TraceparentVisitor::default();
+        attributes.record(&mut visitor);
+
+        if let Some(trace) = visitor.into_context() {
+            extensions.insert(trace);
+        }
+
+        extensions.insert(SpanStart(Instant::now()));
+        drop(extensions);
+
+        if self.span_events.clone() & FmtSpan::NEW == FmtSpan::NEW {
+            if let Err(error) = self.write_span_event(&span, None) {
+                #[cfg(test)]
+                eprintln!("{}", &error)
+            }
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn on_close(&self, id: Id, context: Context<'_, S>) {
+        let span = context.span(&id).expect("Span not found, this is a bug");
+
+        if self.span_events.clone() & FmtSpan::CLOSE == FmtSpan::CLOSE {
+            let elapsed_milliseconds = span
+                .extensions()
+                .get::<SpanStart>()
+                .map(|start| start.0.elapsed().as_millis());
+
+            if let Err(error) = self.write_span_event(&span, elapsed_milliseconds) {
+                #[cfg(test)]
+                eprintln!("{}", &error)
+            }
+        }
+    }
+
     #[allow(unused_variables)]
     fn on_event(&self, event: &Event, context: Context<S>) {
         if let Err(error) = self.visit(event, context) {
@@ -135,7 +526,99 @@ where
     }
 }
 
-struct StackdriverFields;
+/// The `Instant` a span was created, used to measure its `elapsed_milliseconds` on close.
+struct SpanStart(Instant);
+
+/// Pulls a `TraceContext` out of a W3C `traceparent` field recorded on a span, if one was
+/// attached. Fields with any other name (e.g. `X-Cloud-Trace-Context`) are not read.
+#[derive(Default)]
+struct TraceparentVisitor {
+    traceparent: Option<String>,
+}
+
+impl TraceparentVisitor {
+    fn into_context(self) -> Option<TraceContext> {
+        self.traceparent
+            .as_deref()
+            .and_then(TraceContext::from_traceparent)
+    }
+}
+
+impl Visit for TraceparentVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "traceparent" {
+            self.traceparent = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "traceparent" {
+            self.traceparent = Some(format!("{:?}", value).trim_matches('"').to_owned());
+        }
+    }
+}
+
+/// Captures the message and `source()` chain of an `error` field recorded on an `ERROR`-level
+/// event, for inclusion in a Cloud Error Reporting `stack_trace` (see
+/// [`Stackdriver::with_error_reporting`]).
+#[derive(Default)]
+struct ErrorChainVisitor {
+    chain: Vec<String>,
+    message: Option<String>,
+}
+
+impl ErrorChainVisitor {
+    /// Build a Cloud Error Reporting-style `stack_trace` string from the event's formatted
+    /// message, followed by the captured error chain (one `caused by: ` line per error), and
+    /// the event's source location. A plain `tracing::error!("...")` with no `error` field
+    /// produces just the message and location.
+    fn into_stack_trace(self, file: Option<&str>, line: Option<u32>) -> String {
+        let mut stack_trace = self.message.unwrap_or_default();
+
+        for error in self.chain {
+            if !stack_trace.is_empty() {
+                stack_trace.push_str("\ncaused by: ");
+            }
+            stack_trace.push_str(&error);
+        }
+
+        if let (Some(file), Some(line)) = (file, line) {
+            let _ = write!(stack_trace, "\n\tat {}:{}", file, line);
+        }
+
+        stack_trace
+    }
+}
+
+impl Visit for ErrorChainVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        }
+    }
+
+    fn record_error(&mut self, _field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.chain.push(value.to_string());
+
+        let mut source = value.source();
+
+        while let Some(error) = source {
+            self.chain.push(error.to_string());
+            source = error.source();
+        }
+    }
+}
+
+#[derive(Default)]
+struct StackdriverFields {
+    json_fields: JsonFields,
+}
+
+impl<'writer> FormatFields<'writer> for StackdriverFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        self.json_fields.format_fields(writer, fields)
+    }
+}
 
 impl<'a> MakeVisitor<&'a mut dyn Write> for StackdriverFields {
     type Visitor = StackdriverVisitor<'a>;
@@ -166,3 +649,11 @@ struct SourceLocation<'a> {
     file: Option<&'a str>,
     line: Option<u32>,
 }
+
+/// A Google [`Timestamp`](https://protobuf.dev/reference/protobuf/google.protobuf/#timestamp),
+/// used by [`TimestampFormat::UnixTimestamp`].
+#[derive(serde::Serialize)]
+struct UnixTimestamp {
+    seconds: i64,
+    nanos: i32,
+}
@@ -0,0 +1,53 @@
+/*!
+`tracing` Subscriber for structuring Stackdriver-compatible
+[`LogEntry`](https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry)
+
+This crate provides a [`Layer`](https://docs.rs/tracing-subscriber/latest/tracing_subscriber/layer/trait.Layer.html)
+for use with a `tracing` [`Registry`](https://docs.rs/tracing-subscriber/latest/tracing_subscriber/struct.Registry.html)
+that formats `tracing` Spans and Events into properly-structured JSON for consumption by Google Operations Logging
+through the [`jsonPayload`](https://cloud.google.com/logging/docs/structured-logging) field.
+
+This includes the following behaviors and enhancements:
+
+1. `rfc3339`-formatted timestamps for all Events, configurable via
+   [`Stackdriver::with_timestamp_format`] to emit epoch millis or Unix seconds/nanos instead
+2. `severity` derived from `tracing` [`Level`](https://docs.rs/tracing/latest/tracing/struct.Level.html)
+3. `logger` derived from the Event target
+4. `logging.googleapis.com/sourceLocation` derived from the Event's file/line
+5. Span name (as `span_name`) and custom fields included under a `span` key (or a `spans` array, see
+   [`SpanLayout`]) when enabled via [`Stackdriver::with_span_logging`]
+6. `logging.googleapis.com/trace` and `logging.googleapis.com/spanId` correlation with Cloud
+   Trace, parsed from an incoming `traceparent` field (see [`TraceContext::from_traceparent`])
+7. Bunyan-style span lifecycle (`[<span name> - START]`/`[<span name> - END]`) Events, enabled via
+   [`Stackdriver::with_span_events`]
+8. `type.googleapis.com/google.devtools.clouderrorreporting.v1beta1.ReportedErrorEvent`
+   entries for Cloud Error Reporting, enabled via [`Stackdriver::with_error_reporting`]
+9. a `resource` [`MonitoredResource`] and `logging.googleapis.com/labels`, set via
+   [`Stackdriver::with_resource`] and [`Stackdriver::with_labels`]
+10. configured Event fields mapped into a structured `httpRequest` entry, set via
+    [`Stackdriver::with_http_request_fields`]
+
+```
+use tracing_subscriber::{layer::SubscriberExt, Registry};
+use tracing_stackdriver::Stackdriver;
+
+fn main() {
+    let stackdriver = Stackdriver::default(); // writes to std::io::Stdout
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::set_global_default(subscriber).expect("Could not set up global logger");
+}
+```
+*/
+#![deny(missing_docs, unreachable_pub)]
+
+mod error_reporting;
+mod layer;
+mod resource;
+mod trace;
+mod visitor;
+
+pub use self::error_reporting::*;
+pub use self::layer::*;
+pub use self::resource::*;
+pub use self::trace::*;
@@ -0,0 +1,118 @@
+use serde::Serialize;
+use std::{collections::HashMap, time::Duration};
+
+const METADATA_BASE: &str = "http://metadata.google.internal/computeMetadata/v1";
+const METADATA_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A Cloud Logging [monitored resource](https://cloud.google.com/logging/docs/api/v2/resource-list)
+/// describing the environment a log entry was produced in, serialized under the `resource` key
+/// (see [`Stackdriver::with_resource`](crate::Stackdriver::with_resource)).
+#[derive(Clone, Debug, Serialize)]
+pub struct MonitoredResource {
+    #[serde(rename = "type")]
+    resource_type: String,
+    labels: HashMap<String, String>,
+}
+
+impl MonitoredResource {
+    /// Create a new `MonitoredResource` of the given [resource
+    /// type](https://cloud.google.com/logging/docs/api/v2/resource-list), with no labels set.
+    pub fn new(resource_type: impl Into<String>) -> Self {
+        Self {
+            resource_type: resource_type.into(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Set a resource label, e.g. `project_id`, `zone`, or `cluster_name`.
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Detect the [`MonitoredResource`] of the environment this process is running in by querying
+/// the GCE/GKE/Cloud Run metadata server (see
+/// [`Stackdriver::with_resource`](crate::Stackdriver::with_resource)).
+///
+/// Returns `None` if the metadata server isn't reachable, e.g. when running locally.
+pub fn detect_resource() -> Option<MonitoredResource> {
+    let project_id = query_metadata("project/project-id")?;
+    let zone = query_metadata("instance/zone").map(|zone| zone_name(&zone));
+
+    if let Some(cluster_name) = query_metadata("instance/attributes/cluster-name") {
+        let mut resource = MonitoredResource::new("k8s_container")
+            .with_label("project_id", project_id)
+            .with_label("cluster_name", cluster_name);
+
+        if let Some(zone) = zone {
+            resource = resource.with_label("location", zone);
+        }
+
+        if let Ok(namespace_name) = std::env::var("NAMESPACE") {
+            resource = resource.with_label("namespace_name", namespace_name);
+        }
+
+        if let Ok(pod_name) = std::env::var("POD_NAME") {
+            resource = resource.with_label("pod_name", pod_name);
+        }
+
+        if let Ok(container_name) = std::env::var("CONTAINER_NAME") {
+            resource = resource.with_label("container_name", container_name);
+        }
+
+        return Some(resource);
+    }
+
+    if let Ok(service_name) = std::env::var("K_SERVICE") {
+        let mut resource = MonitoredResource::new("cloud_run_revision")
+            .with_label("project_id", project_id)
+            .with_label("service_name", service_name);
+
+        if let Some(zone) = zone {
+            resource = resource.with_label("location", zone);
+        }
+
+        if let Ok(revision_name) = std::env::var("K_REVISION") {
+            resource = resource.with_label("revision_name", revision_name);
+        }
+
+        if let Ok(configuration_name) = std::env::var("K_CONFIGURATION") {
+            resource = resource.with_label("configuration_name", configuration_name);
+        }
+
+        return Some(resource);
+    }
+
+    let mut resource = MonitoredResource::new("gce_instance").with_label("project_id", project_id);
+
+    if let Some(instance_id) = query_metadata("instance/id") {
+        resource = resource.with_label("instance_id", instance_id);
+    }
+
+    if let Some(zone) = zone {
+        resource = resource.with_label("zone", zone);
+    }
+
+    Some(resource)
+}
+
+/// Query a single `computeMetadata/v1` path from the metadata server, returning `None` if the
+/// server isn't reachable or the path doesn't exist.
+fn query_metadata(path: &str) -> Option<String> {
+    let url = format!("{}/{}", METADATA_BASE, path);
+
+    let response = ureq::get(&url)
+        .set("Metadata-Flavor", "Google")
+        .timeout(METADATA_TIMEOUT)
+        .call()
+        .ok()?;
+
+    response.into_string().ok()
+}
+
+/// Extract the zone name from a fully-qualified metadata zone value
+/// (`projects/<project-number>/zones/<zone>`).
+fn zone_name(full: &str) -> String {
+    full.rsplit('/').next().unwrap_or(full).to_owned()
+}
@@ -0,0 +1,61 @@
+/// Distributed trace context associated with a span, used to correlate log entries with
+/// Cloud Trace spans in the GCP console.
+///
+/// A `TraceContext` is recorded into a span's extensions by parsing a `traceparent` field
+/// recorded on it (see [`TraceContext::from_traceparent`]) so that
+/// [`Stackdriver`](crate::Stackdriver) can stamp `logging.googleapis.com/trace` and
+/// `logging.googleapis.com/spanId` onto every log entry emitted while that span is active,
+/// when a project id is set (see [`Stackdriver::with_project_id`](crate::Stackdriver::with_project_id)).
+///
+/// Reading trace context out of a `tracing-opentelemetry` `OtelData` extension instead is
+/// intentionally out of scope here: it would pull `tracing-opentelemetry` (and the
+/// `opentelemetry` crate it depends on) into this crate's dependency tree for every consumer,
+/// not just those who use it. Applications already using `tracing-opentelemetry` can get the
+/// same correlation by also recording a `traceparent` field (most OpenTelemetry SDKs expose the
+/// current span's W3C trace context for exactly this purpose), which this module already knows
+/// how to parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 hex character Cloud Trace trace id
+    pub trace_id: String,
+    /// 16 hex character Cloud Trace span id
+    pub span_id: String,
+    /// whether this trace was sampled
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Parse a W3C `traceparent` header value (`<version>-<trace-id>-<parent-id>-<flags>`),
+    /// into a `TraceContext`.
+    ///
+    /// Returns `None` if the value isn't a well-formed traceparent. Values in the
+    /// `X-Cloud-Trace-Context` format (`TRACE_ID/SPAN_ID;o=1`) are not parsed and also return
+    /// `None`.
+    pub fn from_traceparent(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.split('-');
+        let _version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+
+        if trace_id.len() != 32 || span_id.len() != 16 {
+            return None;
+        }
+
+        if !trace_id.bytes().all(|b| b.is_ascii_hexdigit())
+            || !span_id.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return None;
+        }
+
+        let sampled = u8::from_str_radix(flags, 16)
+            .map(|flags| flags & 0x1 == 0x1)
+            .unwrap_or(false);
+
+        Some(Self {
+            trace_id: trace_id.to_owned(),
+            span_id: span_id.to_owned(),
+            sampled,
+        })
+    }
+}
@@ -0,0 +1,272 @@
+use crate::layer::HttpRequestFields;
+use serde::ser::{SerializeMap, Serializer as _};
+use serde_json::Serializer;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Formatter, Write},
+    io,
+};
+use tracing_core::Field;
+use tracing_subscriber::field::{Visit, VisitFmt, VisitOutput};
+
+/// the EventVisitor implementation for Stackdriver
+pub(crate) struct StackdriverEventVisitor<'a, S: SerializeMap> {
+    values: BTreeMap<&'a str, serde_json::Value>,
+    http_request: BTreeMap<&'static str, serde_json::Value>,
+    http_request_fields: Option<&'a HttpRequestFields>,
+    serializer: S,
+}
+
+impl<'a, S> StackdriverEventVisitor<'a, S>
+where
+    S: SerializeMap,
+{
+    /// Returns a new default visitor using the provided serializer. When `http_request_fields`
+    /// is `Some`, the fields it names are collected into a structured `httpRequest` entry
+    /// instead of flowing into the payload.
+    pub(crate) fn new(serializer: S, http_request_fields: Option<&'a HttpRequestFields>) -> Self {
+        Self {
+            values: BTreeMap::new(),
+            http_request: BTreeMap::new(),
+            http_request_fields,
+            serializer,
+        }
+    }
+
+    /// If `field` is one of the configured `httpRequest` fields, stash `value` under its Cloud
+    /// Logging key and return `true`. Otherwise leave `value` untouched and return `false` so
+    /// the caller can fall back to the regular payload fields.
+    fn record_http_request_field(&mut self, field: &Field, value: serde_json::Value) -> bool {
+        let Some(http_request_fields) = self.http_request_fields else {
+            return false;
+        };
+
+        let key = if field.name() == http_request_fields.method {
+            "requestMethod"
+        } else if field.name() == http_request_fields.url {
+            "requestUrl"
+        } else if field.name() == http_request_fields.status {
+            "status"
+        } else if field.name() == http_request_fields.remote_ip {
+            "remoteIp"
+        } else if field.name() == http_request_fields.user_agent {
+            "userAgent"
+        } else if field.name() == http_request_fields.latency_seconds {
+            let seconds = value.as_f64().unwrap_or_default();
+            self.http_request
+                .insert("latency", serde_json::Value::from(format!("{}s", seconds)));
+            return true;
+        } else {
+            return false;
+        };
+
+        self.http_request.insert(key, value);
+        true
+    }
+}
+
+impl<'a, S> VisitOutput<fmt::Result> for StackdriverEventVisitor<'a, S>
+where
+    S: SerializeMap,
+{
+    fn finish(mut self) -> fmt::Result {
+        let inner = || {
+            if !self.http_request.is_empty() {
+                self.serializer
+                    .serialize_entry("httpRequest", &self.http_request)?;
+            }
+
+            for (key, value) in self.values {
+                self.serializer.serialize_entry(key, &value)?;
+            }
+
+            self.serializer.end()
+        };
+
+        if inner().is_err() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a, S> Visit for StackdriverEventVisitor<'a, S>
+where
+    S: SerializeMap,
+{
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        let value = serde_json::Value::from(value);
+
+        if !self.record_http_request_field(field, value.clone()) {
+            self.values.insert(field.name(), value);
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        let value = serde_json::Value::from(value);
+
+        if !self.record_http_request_field(field, value.clone()) {
+            self.values.insert(field.name(), value);
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        let value = serde_json::Value::from(value);
+
+        if !self.record_http_request_field(field, value.clone()) {
+            self.values.insert(field.name(), value);
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        let value = serde_json::Value::from(value);
+
+        if !self.record_http_request_field(field, value.clone()) {
+            self.values.insert(field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let value = serde_json::Value::from(value);
+
+        if !self.record_http_request_field(field, value.clone()) {
+            self.values.insert(field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = serde_json::Value::from(format!("{:?}", value));
+
+        if !self.record_http_request_field(field, value.clone()) {
+            self.values.insert(field.name(), value);
+        }
+    }
+}
+
+impl<'a, S> fmt::Debug for StackdriverEventVisitor<'a, S>
+where
+    S: SerializeMap,
+{
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_fmt(format_args!(
+            "StackdriverEventVisitor {{ values: {:?} }}",
+            self.values
+        ))
+    }
+}
+
+/// the Visitor implementation for Stackdriver span fields
+pub(crate) struct StackdriverVisitor<'a> {
+    values: BTreeMap<&'a str, serde_json::Value>,
+    writer: &'a mut dyn Write,
+}
+
+impl<'a> StackdriverVisitor<'a> {
+    /// Returns a new default visitor using the provided writer
+    pub(crate) fn new(writer: &'a mut dyn Write) -> Self {
+        Self {
+            values: BTreeMap::new(),
+            writer,
+        }
+    }
+}
+
+impl<'a> VisitFmt for StackdriverVisitor<'a> {
+    fn writer(&mut self) -> &mut dyn Write {
+        self.writer
+    }
+}
+
+impl<'a> VisitOutput<fmt::Result> for StackdriverVisitor<'a> {
+    fn finish(self) -> fmt::Result {
+        let inner = || {
+            let mut serializer = Serializer::new(WriteAdaptor::new(self.writer));
+            let mut map = serializer.serialize_map(None)?;
+
+            for (key, value) in self.values {
+                map.serialize_entry(key, &value)?;
+            }
+
+            map.end()
+        };
+
+        if inner().is_err() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> Visit for StackdriverVisitor<'a> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.values
+            .insert(field.name(), serde_json::Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.values
+            .insert(field.name(), serde_json::Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.values
+            .insert(field.name(), serde_json::Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.values
+            .insert(field.name(), serde_json::Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.values.insert(
+            field.name(),
+            serde_json::Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+impl<'a> fmt::Debug for StackdriverVisitor<'a> {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_fmt(format_args!(
+            "StackdriverVisitor {{ values: {:?} }}",
+            self.values
+        ))
+    }
+}
+
+/// Utility newtype for converting between fmt::Write and io::Write
+struct WriteAdaptor<'a> {
+    fmt_write: &'a mut dyn Write,
+}
+
+impl<'a> WriteAdaptor<'a> {
+    fn new(fmt_write: &'a mut dyn Write) -> Self {
+        Self { fmt_write }
+    }
+}
+
+impl<'a> io::Write for WriteAdaptor<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s =
+            std::str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.fmt_write
+            .write_str(s)
+            .map_err(io::Error::other)?;
+
+        Ok(s.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for WriteAdaptor<'a> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.pad("WriteAdaptor { .. }")
+    }
+}
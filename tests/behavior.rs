@@ -0,0 +1,291 @@
+use serde_json::Value;
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+use tracing_stackdriver::{
+    HttpRequestFields, MonitoredResource, ServiceContext, SpanLayout, Stackdriver, TimestampFormat,
+};
+use tracing_subscriber::{fmt::format::FmtSpan, fmt::MakeWriter, layer::SubscriberExt, Registry};
+
+/// Captures everything written to it so tests can assert on the emitted LogEntry JSON.
+#[derive(Clone, Default)]
+struct CapturingWriter {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl CapturingWriter {
+    /// Parse each captured line as a LogEntry.
+    fn entries(&self) -> Vec<Value> {
+        let buffer = self.buffer.lock().unwrap();
+
+        String::from_utf8_lossy(&buffer)
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("log entry should be valid JSON"))
+            .collect()
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturingWriter {
+    type Writer = CapturingHandle;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        CapturingHandle(self.buffer.clone())
+    }
+}
+
+struct CapturingHandle(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for CapturingHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn includes_cloud_trace_correlation_when_project_id_and_traceparent_are_set() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_project_id("my-project");
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!(
+            "request",
+            traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        let _guard = span.enter();
+        tracing::info!("handled");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert_eq!(
+        entry["logging.googleapis.com/trace"],
+        "projects/my-project/traces/4bf92f3577b34da6a3ce929d0e0e4736"
+    );
+    assert_eq!(entry["logging.googleapis.com/spanId"], "00f067aa0ba902b7");
+    assert_eq!(entry["logging.googleapis.com/trace_sampled"], true);
+}
+
+#[test]
+fn span_flatten_layout_does_not_clobber_a_user_recorded_name_field() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_span_logging(true);
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("handler", name = "my-entity-name");
+        let _guard = span.enter();
+        tracing::info!("handled");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert_eq!(entry["span"]["name"], "my-entity-name");
+    assert_eq!(entry["span"]["span_name"], "handler");
+}
+
+#[test]
+fn span_lifecycle_events_do_not_clobber_a_user_recorded_name_field() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE);
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("handler", name = "my-entity-name");
+        let _guard = span.enter();
+    });
+
+    let entries = writer.entries();
+    let new_entry = &entries[0];
+    let close_entry = &entries[1];
+
+    assert_eq!(new_entry["span"]["name"], "my-entity-name");
+    assert_eq!(new_entry["span"]["span_name"], "handler");
+    assert_eq!(close_entry["span"]["name"], "my-entity-name");
+    assert_eq!(close_entry["span"]["span_name"], "handler");
+    assert!(close_entry["elapsed_milliseconds"].is_number());
+}
+
+#[test]
+fn with_writer_preserves_span_events_configured_before_it() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
+        .with_writer(writer.clone());
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("handler");
+        let _guard = span.enter();
+    });
+
+    let entries = writer.entries();
+
+    assert_eq!(entries.len(), 2);
+    assert!(entries[1]["elapsed_milliseconds"].is_number());
+}
+
+#[test]
+fn with_writer_preserves_span_layout_configured_before_it() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_span_logging(true)
+        .with_span_layout(SpanLayout::Nested)
+        .with_writer(writer.clone());
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("handler");
+        let _guard = span.enter();
+        tracing::info!("handled");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert!(entry["spans"].is_array());
+    assert!(entry.get("span").is_none());
+}
+
+#[test]
+fn error_reporting_uses_the_error_source_chain_when_present() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_error_reporting(ServiceContext::new("svc"));
+    let subscriber = Registry::default().with(stackdriver);
+    let error = io::Error::other("disk full");
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!(error = &error as &dyn std::error::Error, "write failed");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert_eq!(entry["@type"], "type.googleapis.com/google.devtools.clouderrorreporting.v1beta1.ReportedErrorEvent");
+    assert_eq!(entry["serviceContext"]["service"], "svc");
+    assert!(entry["stack_trace"].as_str().unwrap().contains("disk full"));
+}
+
+#[test]
+fn error_reporting_falls_back_to_the_event_message_without_an_error_field() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_error_reporting(ServiceContext::new("svc"));
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("Failed to connect to database");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert!(entry["stack_trace"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to connect to database"));
+}
+
+#[test]
+fn resource_and_labels_are_serialized() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_resource(
+            MonitoredResource::new("gce_instance").with_label("project_id", "my-project"),
+        )
+        .with_labels([("env", "prod")]);
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("hello");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert_eq!(entry["resource"]["type"], "gce_instance");
+    assert_eq!(entry["resource"]["labels"]["project_id"], "my-project");
+    assert_eq!(entry["logging.googleapis.com/labels"]["env"], "prod");
+}
+
+#[test]
+fn configured_fields_are_mapped_into_http_request() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_http_request_fields(HttpRequestFields::default());
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            http.method = "GET",
+            http.status_code = 200,
+            http.latency_seconds = 0.25,
+            "request handled"
+        );
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert_eq!(entry["httpRequest"]["requestMethod"], "GET");
+    assert_eq!(entry["httpRequest"]["status"], 200);
+    assert_eq!(entry["httpRequest"]["latency"], "0.25s");
+    assert!(entry.get("http.method").is_none());
+    assert!(entry.get("http.status_code").is_none());
+}
+
+#[test]
+fn timestamp_format_epoch_millis_serializes_as_a_number() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_timestamp_format(TimestampFormat::EpochMillis);
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("hello");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert!(entry["time"].is_u64());
+}
+
+#[test]
+fn timestamp_format_unix_timestamp_serializes_seconds_and_nanos() {
+    let writer = CapturingWriter::default();
+    let stackdriver = Stackdriver::default()
+        .with_writer(writer.clone())
+        .with_timestamp_format(TimestampFormat::UnixTimestamp);
+    let subscriber = Registry::default().with(stackdriver);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("hello");
+    });
+
+    let entries = writer.entries();
+    let entry = &entries[0];
+
+    assert!(entry["time"]["seconds"].is_i64());
+    assert!(entry["time"]["nanos"].is_i64());
+}